@@ -0,0 +1,133 @@
+//! # Atomic
+//!
+//! Atomic modesetting lets a client stage property changes across any
+//! number of CRTCs, connectors, planes and framebuffers and have the
+//! kernel apply them together as a single, all-or-nothing commit. State is
+//! staged as `(object, property, value)` triples, with property handles
+//! looked up through the [`property`] module.
+//!
+//! [`property`]: ../property/index.html
+
+use control::{self, RawHandle};
+use control::property;
+use result::*;
+use ffi;
+
+/// Validate the request against the kernel's atomic check without applying
+/// it.
+pub const TEST_ONLY: u32 = ffi::DRM_MODE_ATOMIC_TEST_ONLY;
+/// Allow changes that would otherwise require a full modeset (as opposed to
+/// a fast, glitch-free page flip).
+pub const ALLOW_MODESET: u32 = ffi::DRM_MODE_ATOMIC_ALLOW_MODESET;
+/// Return immediately instead of blocking until the commit completes.
+pub const NONBLOCK: u32 = ffi::DRM_MODE_ATOMIC_NONBLOCK;
+/// Request a page flip event on each affected CRTC once the commit lands.
+pub const PAGE_FLIP_EVENT: u32 = ffi::DRM_MODE_PAGE_FLIP_EVENT;
+
+/// A builder that accumulates `(object, property, value)` triples for a
+/// single atomic commit.
+///
+/// Build one up with repeated calls to [`add_property`], then pass it to
+/// [`Device::atomic_commit`].
+///
+/// [`add_property`]: #method.add_property
+/// [`Device::atomic_commit`]: trait.Device.html#method.atomic_commit
+#[derive(Debug, Clone, Default)]
+pub struct AtomicModeReq {
+    items: Vec<(RawHandle, property::Handle, u64)>,
+}
+
+impl AtomicModeReq {
+    /// Creates an empty request.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Stages a single property write against `object`, the raw handle of
+    /// a CRTC, connector, plane or framebuffer.
+    pub fn add_property(&mut self, object: RawHandle, property: property::Handle, value: u64) -> &mut Self {
+        self.items.push((object, property, value));
+        self
+    }
+
+    /// Requests an explicit out-fence for `crtc`'s completion of this
+    /// commit via its `OUT_FENCE_PTR` property.
+    ///
+    /// # Safety
+    ///
+    /// `out_fence_fd` is stored as a bare address and later written
+    /// through by the kernel inside `atomic_commit`; nothing here ties
+    /// that to its lifetime. The caller must ensure it stays valid
+    /// (not moved, not dropped, not reused for anything else) until
+    /// after the commit this request is used in completes.
+    pub unsafe fn add_out_fence(&mut self, crtc: RawHandle, out_fence_ptr: property::Handle, out_fence_fd: *mut ::std::os::unix::io::RawFd) -> &mut Self {
+        self.add_property(crtc, out_fence_ptr, out_fence_fd as u64)
+    }
+
+    /// Defers this commit on `object` until `fd`'s fence has signaled, via
+    /// its `IN_FENCE_FD` property.
+    pub fn add_in_fence(&mut self, object: RawHandle, in_fence_fd: property::Handle, fd: ::std::os::unix::io::RawFd) -> &mut Self {
+        self.add_property(object, in_fence_fd, fd as u64)
+    }
+
+    /// Returns true if no property writes have been staged yet.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// Extension of [`control::Device`] adding the atomic commit ioctl.
+///
+/// [`control::Device`]: ../trait.Device.html
+pub trait Device: control::Device {
+    /// Submits an atomic request built with [`AtomicModeReq`].
+    ///
+    /// `flags` is any combination of [`TEST_ONLY`], [`ALLOW_MODESET`],
+    /// [`NONBLOCK`] and [`PAGE_FLIP_EVENT`].
+    ///
+    /// [`AtomicModeReq`]: struct.AtomicModeReq.html
+    /// [`TEST_ONLY`]: constant.TEST_ONLY.html
+    /// [`ALLOW_MODESET`]: constant.ALLOW_MODESET.html
+    /// [`NONBLOCK`]: constant.NONBLOCK.html
+    /// [`PAGE_FLIP_EVENT`]: constant.PAGE_FLIP_EVENT.html
+    fn atomic_commit(&self, flags: u32, req: &AtomicModeReq) -> Result<()> {
+        // The kernel groups triples by object: consecutive items sharing an
+        // object id are collapsed into one run with its own property
+        // count, mirroring how `drm_mode_atomic_ioctl` walks the arrays.
+        let mut object_ids = Vec::new();
+        let mut count_props = Vec::new();
+        let mut prop_ids = Vec::with_capacity(req.items.len());
+        let mut prop_values = Vec::with_capacity(req.items.len());
+
+        let mut i = 0;
+        while i < req.items.len() {
+            let object = req.items[i].0;
+            let start = i;
+
+            while i < req.items.len() && req.items[i].0 == object {
+                prop_ids.push(req.items[i].1.as_raw());
+                prop_values.push(req.items[i].2);
+                i += 1;
+            }
+
+            object_ids.push(object);
+            count_props.push((i - start) as u32);
+        }
+
+        let mut raw: ffi::drm_mode_atomic = Default::default();
+        raw.flags = flags;
+        raw.count_objs = object_ids.len() as u32;
+        raw.objs_ptr = object_ids.as_mut_ptr() as u64;
+        raw.count_props_ptr = count_props.as_mut_ptr() as u64;
+        raw.props_ptr = prop_ids.as_mut_ptr() as u64;
+        raw.prop_values_ptr = prop_values.as_mut_ptr() as u64;
+
+        unsafe {
+            try!(ffi::ioctl_mode_atomic(self.as_raw_fd(), &mut raw));
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Device for T where T: control::Device {}
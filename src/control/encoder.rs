@@ -4,6 +4,8 @@
 //! data of the CRTC and encodes it into a format the connector understands.
 
 use control::{self, ResourceHandle, ResourceInfo};
+use control::atomic;
+use control::property;
 use result::*;
 use ffi;
 
@@ -64,18 +66,43 @@ impl Info {
         }
     }
 
-    /// Returns true if the encoder supports a given `crtc::Handle`
-    pub fn supports_crtc(&self, crtc: control::crtc::Handle) -> bool {
-        use ::std::num::Wrapping;
+    /// Returns the CRTCs this encoder can be routed to.
+    ///
+    /// `possible_crtcs` is a bitmask indexed by a CRTC's *position* in
+    /// `handles.crtcs()`, not by its handle value, so resolving it requires
+    /// the device's current `ResourceHandles` as context. This is the
+    /// correct replacement for testing the raw bitmask by handle.
+    pub fn possible_crtcs(&self, handles: &control::ResourceHandles) -> Vec<control::crtc::Handle> {
+        handles.crtcs().iter().enumerate()
+            .filter(|&(n, _)| self.possible_crtcs & (1 << n) != 0)
+            .map(|(_, &h)| h)
+            .collect()
+    }
 
-        self.possible_crtcs & (Wrapping(1u32) << crtc.as_raw() as usize).0 != 0
+    /// Returns the other encoders this encoder can share a CRTC with
+    /// (mirrored output).
+    ///
+    /// Like [`possible_crtcs`], `possible_clones` is a bitmask indexed by
+    /// position, but into `handles.encoders()` rather than the CRTC list,
+    /// since cloning is a relationship between encoders.
+    ///
+    /// [`possible_crtcs`]: #method.possible_crtcs
+    pub fn possible_clones(&self, handles: &control::ResourceHandles) -> Vec<Handle> {
+        handles.encoders().iter().enumerate()
+            .filter(|&(n, _)| self.possible_clones & (1 << n) != 0)
+            .map(|(_, &h)| h)
+            .collect()
     }
 
-    /// Returns true if the encoder supports cloning via a given `crtc::Handle`
-    pub fn supports_clone(&self, crtc: control::crtc::Handle) -> bool {
-        use ::std::num::Wrapping;
+    /// Returns true if the encoder supports a given `crtc::Handle`
+    pub fn supports_crtc(&self, handles: &control::ResourceHandles, crtc: control::crtc::Handle) -> bool {
+        self.possible_crtcs(handles).contains(&crtc)
+    }
 
-        self.possible_clones & (Wrapping(1u32) << crtc.as_raw() as usize).0 != 0
+    /// Returns true if the encoder can clone (mirror) onto a given
+    /// `encoder::Handle`
+    pub fn supports_clone(&self, handles: &control::ResourceHandles, encoder: Handle) -> bool {
+        self.possible_clones(handles).contains(&encoder)
     }
 }
 
@@ -139,3 +166,285 @@ impl ::std::fmt::Debug for Handle {
         write!(f, "encoder::Handle({})", self.0)
     }
 }
+
+/// Finds a free CRTC to drive a connector through one of `encoders`.
+///
+/// Tries each encoder's [`Info::possible_crtcs`] in turn, skipping any CRTC
+/// that's already another encoder's [`Info::current_crtc`] or that appears
+/// in `reserved`. Returns `Ok(None)` if none is free.
+///
+/// [`Info::possible_crtcs`]: Info.t.html#method.possible_crtcs
+/// [`Info::current_crtc`]: Info.t.html#method.current_crtc
+pub fn route_to_free_crtc<T>(
+    device: &T,
+    encoders: &[Handle],
+    resources: &control::ResourceHandles,
+    reserved: &[control::crtc::Handle],
+) -> Result<Option<(Handle, control::crtc::Handle)>>
+    where T: control::Device {
+
+    // Load every encoder's `Info` exactly once up front rather than
+    // re-fetching it per candidate CRTC: that's what lets us test a CRTC
+    // against "is some other encoder already driving it" as a lookup
+    // against this list instead of an ioctl per check.
+    let mut all_infos = Vec::with_capacity(resources.encoders().len());
+    for &handle in resources.encoders() {
+        all_infos.push(try!(Info::load_from_device(device, handle)));
+    }
+
+    for &enc_handle in encoders {
+        let info = match all_infos.iter().find(|i| i.handle() == enc_handle) {
+            Some(info) => *info,
+            None => try!(Info::load_from_device(device, enc_handle)),
+        };
+
+        for crtc in info.possible_crtcs(resources) {
+            if reserved.contains(&crtc) {
+                continue;
+            }
+
+            let in_use = all_infos.iter().any(|other| {
+                other.handle() != enc_handle && other.current_crtc() == Some(crtc)
+            });
+
+            if !in_use {
+                return Ok(Some((enc_handle, crtc)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Atomic-mode counterpart to [`route_to_free_crtc`]: routes the same way,
+/// then stages the connector's `CRTC_ID` property on `req`, so the route is
+/// applied by an atomic commit rather than the legacy setcrtc ioctl.
+/// Returns `Ok(None)` if no CRTC is free or the connector has no `CRTC_ID`
+/// property.
+///
+/// [`route_to_free_crtc`]: fn.route_to_free_crtc.html
+pub fn route_to_free_crtc_atomic<T>(
+    device: &T,
+    connector: control::connector::Handle,
+    encoders: &[Handle],
+    resources: &control::ResourceHandles,
+    reserved: &[control::crtc::Handle],
+    req: &mut atomic::AtomicModeReq,
+) -> Result<Option<control::crtc::Handle>>
+    where T: atomic::Device {
+
+    let route = try!(route_to_free_crtc(device, encoders, resources, reserved));
+    let crtc = match route {
+        Some((_, crtc)) => crtc,
+        None => return Ok(None),
+    };
+
+    let crtc_id_prop = try!(property::find_by_name(
+        device, connector.as_raw(), ffi::DRM_MODE_OBJECT_CONNECTOR, "CRTC_ID"));
+
+    // No `CRTC_ID` property means this connector can't actually be routed
+    // under atomic (wrong object type, or a legacy-only driver); report
+    // that as "no route" rather than staging nothing and claiming success.
+    let (prop, _) = match crtc_id_prop {
+        Some(pair) => pair,
+        None => return Ok(None),
+    };
+
+    req.add_property(connector.as_raw(), prop, crtc.as_raw() as u64);
+
+    Ok(Some(crtc))
+}
+
+/// Groups encoders that can share a single CRTC to drive identical output
+/// (mirroring).
+///
+/// An edge requires both encoders to list each other in
+/// [`Info::possible_clones`], since hardware occasionally reports an
+/// asymmetric mask. Returns the maximal groups where every pair can
+/// mirror together.
+///
+/// [`Info::possible_clones`]: Info.t.html#method.possible_clones
+pub fn clone_groups(encoders: &[Info], resources: &control::ResourceHandles) -> Vec<Vec<Handle>> {
+    let n = encoders.len();
+    let mut adjacency = vec![vec![false; n]; n];
+
+    for (i, a) in encoders.iter().enumerate() {
+        let a_clones = a.possible_clones(resources);
+
+        for (j, b) in encoders.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+
+            let b_clones = b.possible_clones(resources);
+            if a_clones.contains(&b.handle()) && b_clones.contains(&a.handle()) {
+                adjacency[i][j] = true;
+            }
+        }
+    }
+
+    // A mirror group is only legal if *every* pair in it is mutually
+    // clonable, not just transitively connected, so this looks for maximal
+    // cliques (Bron-Kerbosch) rather than connected components.
+    let mut cliques = Vec::new();
+    bron_kerbosch(&adjacency, Vec::new(), (0..n).collect(), Vec::new(), &mut cliques);
+
+    cliques.into_iter()
+        .filter(|clique| clique.len() > 1)
+        .map(|clique| clique.into_iter().map(|i| encoders[i].handle()).collect())
+        .collect()
+}
+
+fn bron_kerbosch(
+    adjacency: &[Vec<bool>],
+    r: Vec<usize>,
+    mut p: Vec<usize>,
+    mut x: Vec<usize>,
+    cliques: &mut Vec<Vec<usize>>,
+) {
+    if p.is_empty() && x.is_empty() {
+        cliques.push(r);
+        return;
+    }
+
+    for v in p.clone() {
+        let neighbors = |u: &usize| adjacency[v][*u];
+
+        let mut next_r = r.clone();
+        next_r.push(v);
+        let next_p: Vec<usize> = p.iter().cloned().filter(neighbors).collect();
+        let next_x: Vec<usize> = x.iter().cloned().filter(neighbors).collect();
+        bron_kerbosch(adjacency, next_r, next_p, next_x, cliques);
+
+        p.retain(|u| u != &v);
+        x.push(v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource_handles(crtcs: Vec<control::crtc::Handle>, encoders: Vec<Handle>) -> control::ResourceHandles {
+        control::ResourceHandles {
+            crtcs: crtcs,
+            encoders: encoders,
+            ..Default::default()
+        }
+    }
+
+    fn encoder_info(possible_crtcs: u32, possible_clones: u32) -> Info {
+        Info {
+            handle: Handle::from_raw(0),
+            crtc_id: control::crtc::Handle::from_raw(0),
+            enc_type: Type::None,
+            possible_crtcs: possible_crtcs,
+            possible_clones: possible_clones,
+        }
+    }
+
+    #[test]
+    fn possible_crtcs_resolves_by_position_not_handle_value() {
+        // Handle values deliberately don't match their position, so
+        // resolving by handle value (the old, buggy behavior) would pick
+        // the wrong CRTC.
+        let crtc_a = control::crtc::Handle::from_raw(40);
+        let crtc_b = control::crtc::Handle::from_raw(7);
+        let res = resource_handles(vec![crtc_a, crtc_b], vec![]);
+
+        let enc = encoder_info(0b10, 0);
+        assert_eq!(enc.possible_crtcs(&res), vec![crtc_b]);
+    }
+
+    #[test]
+    fn supports_crtc_agrees_with_possible_crtcs() {
+        let crtc_a = control::crtc::Handle::from_raw(5);
+        let crtc_b = control::crtc::Handle::from_raw(6);
+        let res = resource_handles(vec![crtc_a, crtc_b], vec![]);
+
+        let enc = encoder_info(0b01, 0);
+        assert!(enc.supports_crtc(&res, crtc_a));
+        assert!(!enc.supports_crtc(&res, crtc_b));
+    }
+
+    #[test]
+    fn possible_clones_resolves_against_encoder_list() {
+        let enc_a = Handle::from_raw(1);
+        let enc_b = Handle::from_raw(99);
+        let res = resource_handles(vec![], vec![enc_a, enc_b]);
+
+        let enc = encoder_info(0, 0b10);
+        assert_eq!(enc.possible_clones(&res), vec![enc_b]);
+    }
+
+    #[test]
+    fn supports_clone_agrees_with_possible_clones() {
+        let enc_a = Handle::from_raw(1);
+        let enc_b = Handle::from_raw(2);
+        let res = resource_handles(vec![], vec![enc_a, enc_b]);
+
+        let enc = encoder_info(0, 0b01);
+        assert!(enc.supports_clone(&res, enc_a));
+        assert!(!enc.supports_clone(&res, enc_b));
+    }
+
+    fn encoder_info_with_handle(handle: Handle, possible_clones: u32) -> Info {
+        Info {
+            handle: handle,
+            crtc_id: control::crtc::Handle::from_raw(0),
+            enc_type: Type::None,
+            possible_crtcs: 0,
+            possible_clones: possible_clones,
+        }
+    }
+
+    #[test]
+    fn clone_groups_rejects_asymmetric_clone_masks() {
+        // c reports a as clonable, but a does not report c back, so the
+        // a-c pairing must not be treated as mirrorable.
+        let handle_a = Handle::from_raw(0);
+        let handle_b = Handle::from_raw(1);
+        let handle_c = Handle::from_raw(2);
+        let res = resource_handles(vec![], vec![handle_a, handle_b, handle_c]);
+
+        let a = encoder_info_with_handle(handle_a, 0b010);
+        let b = encoder_info_with_handle(handle_b, 0b001);
+        let c = encoder_info_with_handle(handle_c, 0b001);
+
+        let groups = clone_groups(&[a, b, c], &res);
+        assert_eq!(groups, vec![vec![handle_a, handle_b]]);
+    }
+
+    #[test]
+    fn clone_groups_splits_non_cliques() {
+        // a-b and b-c are valid clone edges, but a-c is not, so {a, b, c}
+        // must come back as two separate groups rather than one trio.
+        let handle_a = Handle::from_raw(0);
+        let handle_b = Handle::from_raw(1);
+        let handle_c = Handle::from_raw(2);
+        let res = resource_handles(vec![], vec![handle_a, handle_b, handle_c]);
+
+        let a = encoder_info_with_handle(handle_a, 0b010);
+        let b = encoder_info_with_handle(handle_b, 0b101);
+        let c = encoder_info_with_handle(handle_c, 0b010);
+
+        let mut groups = clone_groups(&[a, b, c], &res);
+        groups.sort_by_key(|g| g.iter().map(|h| h.as_raw()).min().unwrap());
+        assert_eq!(groups, vec![vec![handle_a, handle_b], vec![handle_b, handle_c]]);
+    }
+
+    #[test]
+    fn clone_groups_finds_a_full_clique() {
+        let handle_a = Handle::from_raw(0);
+        let handle_b = Handle::from_raw(1);
+        let handle_c = Handle::from_raw(2);
+        let res = resource_handles(vec![], vec![handle_a, handle_b, handle_c]);
+
+        let a = encoder_info_with_handle(handle_a, 0b110);
+        let b = encoder_info_with_handle(handle_b, 0b101);
+        let c = encoder_info_with_handle(handle_c, 0b011);
+
+        let groups = clone_groups(&[a, b, c], &res);
+        assert_eq!(groups, vec![vec![handle_a, handle_b, handle_c]]);
+    }
+}
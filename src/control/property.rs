@@ -0,0 +1,250 @@
+//! # Property
+//!
+//! Properties are the generic, self-describing mechanism atomic modesetting
+//! uses to change object state. Every mode object (CRTC, connector, plane,
+//! framebuffer) exposes a set of properties, each with a name, a value type
+//! and, depending on that type, a set of legal values or enum names. Callers
+//! look properties up by name (`"CRTC_ID"`, `"MODE_ID"`, `"FB_ID"`,
+//! `"SRC_X"`, ...) rather than hardcoding handles, since property handles
+//! are not stable across devices.
+
+use control::{self, ResourceHandle, ResourceInfo};
+use result::*;
+use ffi;
+
+/// A [`ResourceHandle`] for a property.
+///
+/// [`ResourceHandle`]: ResourceHandle.t.html
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle(control::RawHandle);
+
+/// The kind of value a property holds, as reported by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Range,
+    SignedRange,
+    Enum,
+    Bitmask,
+    Object,
+    Blob,
+}
+
+/// A single named value of an `Enum` or `Bitmask` property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumValue {
+    name: String,
+    value: u64,
+}
+
+impl EnumValue {
+    /// The name this value is exposed under (e.g. `"On"` for a DPMS
+    /// property).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The raw value a property is set to when this enum member is chosen.
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+/// A [`ResourceInfo`] describing a property and the values it accepts.
+///
+/// [`ResourceInfo`]: ResourceInfo.t.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    handle: Handle,
+    name: String,
+    value_type: ValueType,
+    values: Vec<u64>,
+    enums: Vec<EnumValue>,
+}
+
+impl Info {
+    /// Returns the property's name, as looked up by callers wanting a
+    /// well-known property like `CRTC_ID` or `MODE_ID`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the kind of value this property holds.
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    /// For a `Range`/`SignedRange` property, the `[min, max]` bounds; for
+    /// an `Object` property, the allowed object type.
+    pub fn values(&self) -> &[u64] {
+        &self.values
+    }
+
+    /// For an `Enum`/`Bitmask` property, the named values it can take.
+    pub fn enums(&self) -> &[EnumValue] {
+        &self.enums
+    }
+}
+
+impl ResourceHandle for Handle {
+    fn from_raw(raw: control::RawHandle) -> Self {
+        Handle(raw)
+    }
+
+    fn as_raw(&self) -> control::RawHandle {
+        self.0
+    }
+}
+
+impl ResourceInfo for Info {
+    type Handle = Handle;
+
+    fn load_from_device<T>(device: &T, handle: Handle) -> Result<Self>
+        where T: control::Device {
+
+        let mut raw: ffi::drm_mode_get_property = Default::default();
+        raw.prop_id = handle.as_raw();
+
+        // As with `properties()`, a first call with no buffers attached
+        // just learns `count_values`/`count_enum_blobs`; a second call is
+        // needed to actually fetch them.
+        unsafe {
+            try!(ffi::ioctl_mode_getproperty(device.as_raw_fd(), &mut raw));
+        }
+
+        let mut values = vec![0u64; raw.count_values as usize];
+        let mut enum_blobs: Vec<ffi::drm_mode_property_enum> =
+            vec![Default::default(); raw.count_enum_blobs as usize];
+        raw.values_ptr = values.as_mut_ptr() as u64;
+        raw.enum_blob_ptr = enum_blobs.as_mut_ptr() as u64;
+
+        unsafe {
+            try!(ffi::ioctl_mode_getproperty(device.as_raw_fd(), &mut raw));
+        }
+        values.truncate(raw.count_values as usize);
+        enum_blobs.truncate(raw.count_enum_blobs as usize);
+
+        let name = unsafe {
+            ::std::ffi::CStr::from_ptr(raw.name.as_ptr())
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        let value_type = ValueType::from(raw.flags);
+        let enums = match value_type {
+            ValueType::Enum | ValueType::Bitmask => enum_blobs.iter()
+                .map(|e| EnumValue {
+                    name: unsafe {
+                        ::std::ffi::CStr::from_ptr(e.name.as_ptr())
+                            .to_string_lossy()
+                            .into_owned()
+                    },
+                    value: e.value,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            handle: handle,
+            name: name,
+            value_type: value_type,
+            values: values,
+            enums: enums,
+        })
+    }
+
+    fn handle(&self) -> Self::Handle { self.handle }
+}
+
+impl From<u32> for ValueType {
+    fn from(flags: u32) -> Self {
+        if flags & ffi::DRM_MODE_PROP_RANGE != 0 {
+            ValueType::Range
+        } else if flags & ffi::DRM_MODE_PROP_SIGNED_RANGE != 0 {
+            ValueType::SignedRange
+        } else if flags & ffi::DRM_MODE_PROP_ENUM != 0 {
+            ValueType::Enum
+        } else if flags & ffi::DRM_MODE_PROP_BITMASK != 0 {
+            ValueType::Bitmask
+        } else if flags & ffi::DRM_MODE_PROP_OBJECT != 0 {
+            ValueType::Object
+        } else {
+            ValueType::Blob
+        }
+    }
+}
+
+impl ::std::fmt::Debug for Handle {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "property::Handle({})", self.0)
+    }
+}
+
+/// Returns the `(property::Handle, value)` pairs currently set on a mode
+/// object (a CRTC, connector, plane or framebuffer, identified by its raw
+/// handle and `DRM_MODE_OBJECT_*` type).
+pub fn properties<T>(device: &T, object: control::RawHandle, object_type: u32) -> Result<Vec<(Handle, u64)>>
+    where T: control::Device {
+
+    let mut raw: ffi::drm_mode_obj_get_properties = Default::default();
+    raw.obj_id = object;
+    raw.obj_type = object_type;
+
+    // First call with no buffers attached just to learn `count_props`; the
+    // kernel never copies into `prop_ids`/`prop_values` unless they already
+    // point at a buffer of that size, so a second call is required.
+    unsafe {
+        try!(ffi::ioctl_mode_obj_getproperties(device.as_raw_fd(), &mut raw));
+    }
+
+    let mut ids = vec![0 as control::RawHandle; raw.count_props as usize];
+    let mut values = vec![0u64; raw.count_props as usize];
+    raw.prop_ids = ids.as_mut_ptr() as u64;
+    raw.prop_values = values.as_mut_ptr() as u64;
+
+    unsafe {
+        try!(ffi::ioctl_mode_obj_getproperties(device.as_raw_fd(), &mut raw));
+    }
+
+    let count = (raw.count_props as usize).min(ids.len());
+    let pairs = (0..count)
+        .map(|n| (Handle::from_raw(ids[n]), values[n]))
+        .collect();
+
+    Ok(pairs)
+}
+
+/// Returns every property set on `object`, resolved to full [`Info`]
+/// rather than just a handle.
+///
+/// Prefer this over repeated [`find_by_name`] calls when a caller needs to
+/// look up more than one property on the same object (e.g. routing a
+/// connector under atomic): each lookup against the returned slice is a
+/// plain scan, whereas `find_by_name` re-resolves every property's name
+/// from scratch each time it's called.
+///
+/// [`find_by_name`]: fn.find_by_name.html
+pub fn properties_with_names<T>(device: &T, object: control::RawHandle, object_type: u32) -> Result<Vec<(Info, u64)>>
+    where T: control::Device {
+
+    let mut resolved = Vec::new();
+    for (handle, value) in try!(properties(device, object, object_type)) {
+        resolved.push((try!(Info::load_from_device(device, handle)), value));
+    }
+
+    Ok(resolved)
+}
+
+/// Looks up a named property on a mode object, the way userspace
+/// conventionally locates well-known properties like `CRTC_ID` or
+/// `MODE_ID` rather than hardcoding their handles.
+///
+/// Returns `Ok(None)` if `object` has no property by that name.
+pub fn find_by_name<T>(device: &T, object: control::RawHandle, object_type: u32, name: &str) -> Result<Option<(Handle, u64)>>
+    where T: control::Device {
+
+    let resolved = try!(properties_with_names(device, object, object_type));
+    Ok(resolved.into_iter()
+        .find(|&(ref info, _)| info.name() == name)
+        .map(|(info, value)| (info.handle(), value)))
+}
@@ -0,0 +1,254 @@
+//! # Sync Object
+//!
+//! A sync object (`syncobj`) is a kernel-mediated fence, exportable to and
+//! importable from file descriptors (including `sync_file`s and PRIME
+//! fds), that lets userspace hand GPU work to a consumer without blocking
+//! the CPU on it. Pair with [`control::atomic::AtomicModeReq::add_out_fence`].
+//!
+//! [`control::atomic::AtomicModeReq::add_out_fence`]: ../control/atomic/struct.AtomicModeReq.html#method.add_out_fence
+
+use std::os::unix::io::RawFd;
+
+use result::*;
+use ffi;
+use Device;
+
+/// A handle to a kernel DRM sync object.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle(u32);
+
+impl Handle {
+    /// Returns the raw kernel handle.
+    pub fn as_raw(&self) -> u32 {
+        self.0
+    }
+}
+
+impl ::std::fmt::Debug for Handle {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "syncobj::Handle({})", self.0)
+    }
+}
+
+/// A point on a syncobj's timeline, used by the [`timeline`] operations.
+///
+/// [`timeline`]: timeline/index.html
+pub type TimelinePoint = u64;
+
+/// Creates a new sync object.
+///
+/// If `signaled` is true, the object starts out already signaled, which is
+/// useful as a placeholder fence before any real work has been submitted
+/// against it.
+pub fn create<T: Device>(device: &T, signaled: bool) -> Result<Handle> {
+    let mut raw: ffi::drm_syncobj_create = Default::default();
+    if signaled {
+        raw.flags = ffi::DRM_SYNCOBJ_CREATE_SIGNALED;
+    }
+
+    unsafe {
+        try!(ffi::ioctl_syncobj_create(device.as_raw_fd(), &mut raw));
+    }
+
+    Ok(Handle(raw.handle))
+}
+
+/// Destroys a sync object.
+pub fn destroy<T: Device>(device: &T, handle: Handle) -> Result<()> {
+    let mut raw: ffi::drm_syncobj_destroy = Default::default();
+    raw.handle = handle.as_raw();
+
+    unsafe {
+        try!(ffi::ioctl_syncobj_destroy(device.as_raw_fd(), &mut raw));
+    }
+
+    Ok(())
+}
+
+/// Exports a sync object as a file descriptor.
+///
+/// Pass `sync_file: true` to export a `sync_file` snapshotting the
+/// object's current fence, for handing to code that only understands
+/// sync_files (such as another driver's `IN_FENCE_FD`), rather than a
+/// duplicate handle to the syncobj itself.
+pub fn export<T: Device>(device: &T, handle: Handle, sync_file: bool) -> Result<RawFd> {
+    let mut raw: ffi::drm_syncobj_handle = Default::default();
+    raw.handle = handle.as_raw();
+    if sync_file {
+        raw.flags = ffi::DRM_SYNCOBJ_HANDLE_TO_FD_FLAGS_EXPORT_SYNC_FILE;
+    }
+
+    unsafe {
+        try!(ffi::ioctl_syncobj_handle_to_fd(device.as_raw_fd(), &mut raw));
+    }
+
+    Ok(raw.fd)
+}
+
+/// Imports a syncobj from a file descriptor previously produced by
+/// [`export`].
+///
+/// [`export`]: fn.export.html
+pub fn import<T: Device>(device: &T, fd: RawFd, sync_file: bool) -> Result<Handle> {
+    let mut raw: ffi::drm_syncobj_handle = Default::default();
+    raw.fd = fd;
+    if sync_file {
+        raw.flags = ffi::DRM_SYNCOBJ_FD_TO_HANDLE_FLAGS_IMPORT_SYNC_FILE;
+    }
+
+    unsafe {
+        try!(ffi::ioctl_syncobj_fd_to_handle(device.as_raw_fd(), &mut raw));
+    }
+
+    Ok(Handle(raw.handle))
+}
+
+/// Blocks until one (`wait_all: false`) or all (`wait_all: true`) of
+/// `handles` has a signaled fence, or `timeout_nsec` (measured against
+/// `CLOCK_MONOTONIC`) elapses.
+///
+/// Returns the index into `handles` of a syncobj observed signaled.
+pub fn wait<T: Device>(device: &T, handles: &[Handle], timeout_nsec: i64, wait_all: bool) -> Result<usize> {
+    let raw_handles: Vec<u32> = handles.iter().map(Handle::as_raw).collect();
+
+    let mut raw: ffi::drm_syncobj_wait = Default::default();
+    raw.handles = raw_handles.as_ptr() as u64;
+    raw.count_handles = raw_handles.len() as u32;
+    raw.timeout_nsec = timeout_nsec;
+    if wait_all {
+        raw.flags |= ffi::DRM_SYNCOBJ_WAIT_FLAGS_WAIT_ALL;
+    }
+
+    unsafe {
+        try!(ffi::ioctl_syncobj_wait(device.as_raw_fd(), &mut raw));
+    }
+
+    Ok(raw.first_signaled as usize)
+}
+
+/// Resets (un-signals) a set of sync objects.
+pub fn reset<T: Device>(device: &T, handles: &[Handle]) -> Result<()> {
+    let raw_handles: Vec<u32> = handles.iter().map(Handle::as_raw).collect();
+
+    let mut raw: ffi::drm_syncobj_array = Default::default();
+    raw.handles = raw_handles.as_ptr() as u64;
+    raw.count_handles = raw_handles.len() as u32;
+
+    unsafe {
+        try!(ffi::ioctl_syncobj_reset(device.as_raw_fd(), &mut raw));
+    }
+
+    Ok(())
+}
+
+/// Signals a set of sync objects immediately, with no real GPU work behind
+/// them.
+pub fn signal<T: Device>(device: &T, handles: &[Handle]) -> Result<()> {
+    let raw_handles: Vec<u32> = handles.iter().map(Handle::as_raw).collect();
+
+    let mut raw: ffi::drm_syncobj_array = Default::default();
+    raw.handles = raw_handles.as_ptr() as u64;
+    raw.count_handles = raw_handles.len() as u32;
+
+    unsafe {
+        try!(ffi::ioctl_syncobj_signal(device.as_raw_fd(), &mut raw));
+    }
+
+    Ok(())
+}
+
+/// Timeline operations on a syncobj, for drivers that track a
+/// monotonically increasing counter of completed points instead of a
+/// single binary fence.
+pub mod timeline {
+    use super::*;
+
+    /// Blocks until one (`wait_all: false`) or all (`wait_all: true`) of
+    /// `handles` have reached at least the matching entry of `points`, or
+    /// `timeout_nsec` elapses.
+    ///
+    /// Returns the index into `handles` of a syncobj observed signaled.
+    pub fn wait<T: Device>(
+        device: &T,
+        handles: &[Handle],
+        points: &[TimelinePoint],
+        timeout_nsec: i64,
+        wait_all: bool,
+    ) -> Result<usize> {
+        assert_eq!(handles.len(), points.len());
+
+        let raw_handles: Vec<u32> = handles.iter().map(Handle::as_raw).collect();
+
+        let mut raw: ffi::drm_syncobj_timeline_wait = Default::default();
+        raw.handles = raw_handles.as_ptr() as u64;
+        raw.points = points.as_ptr() as u64;
+        raw.count_handles = raw_handles.len() as u32;
+        raw.timeout_nsec = timeout_nsec;
+        if wait_all {
+            raw.flags |= ffi::DRM_SYNCOBJ_WAIT_FLAGS_WAIT_ALL;
+        }
+
+        unsafe {
+            try!(ffi::ioctl_syncobj_timeline_wait(device.as_raw_fd(), &mut raw));
+        }
+
+        Ok(raw.first_signaled as usize)
+    }
+
+    /// Advances `handle` to `point`, signaling any waiters for points at or
+    /// before it.
+    pub fn signal<T: Device>(device: &T, handle: Handle, point: TimelinePoint) -> Result<()> {
+        let raw_handle = handle.as_raw();
+
+        let mut raw: ffi::drm_syncobj_timeline_array = Default::default();
+        raw.handles = (&raw_handle as *const u32) as u64;
+        raw.points = (&point as *const u64) as u64;
+        raw.count_handles = 1;
+
+        unsafe {
+            try!(ffi::ioctl_syncobj_timeline_signal(device.as_raw_fd(), &mut raw));
+        }
+
+        Ok(())
+    }
+
+    /// Reads back each handle's current timeline point.
+    pub fn query<T: Device>(device: &T, handles: &[Handle]) -> Result<Vec<TimelinePoint>> {
+        let raw_handles: Vec<u32> = handles.iter().map(Handle::as_raw).collect();
+        let mut points = vec![0u64; handles.len()];
+
+        let mut raw: ffi::drm_syncobj_timeline_array = Default::default();
+        raw.handles = raw_handles.as_ptr() as u64;
+        raw.points = points.as_mut_ptr() as u64;
+        raw.count_handles = raw_handles.len() as u32;
+
+        unsafe {
+            try!(ffi::ioctl_syncobj_query(device.as_raw_fd(), &mut raw));
+        }
+
+        Ok(points)
+    }
+
+    /// Moves a binary syncobj's current fence onto `dst`'s timeline at
+    /// `dst_point`, the bridge used when mixing timeline and binary
+    /// syncobjs.
+    pub fn transfer<T: Device>(
+        device: &T,
+        dst: Handle,
+        dst_point: TimelinePoint,
+        src: Handle,
+        src_point: TimelinePoint,
+    ) -> Result<()> {
+        let mut raw: ffi::drm_syncobj_transfer = Default::default();
+        raw.dst_handle = dst.as_raw();
+        raw.dst_point = dst_point;
+        raw.src_handle = src.as_raw();
+        raw.src_point = src_point;
+
+        unsafe {
+            try!(ffi::ioctl_syncobj_transfer(device.as_raw_fd(), &mut raw));
+        }
+
+        Ok(())
+    }
+}